@@ -0,0 +1,229 @@
+use slog::Logger;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use controller::{self, Workers};
+use job_queue::JobQueue;
+use router::Router;
+
+/// Run the admin HTTP server: read-only cluster introspection, a drain
+/// endpoint, and a Prometheus `/metrics` endpoint, spawned alongside
+/// `api-srv` so the recipe-upload path stays untouched by operators polling
+/// for status.
+pub fn run(
+    workers: Workers,
+    recipe_version: Arc<Mutex<u64>>,
+    jobs: Arc<Mutex<JobQueue>>,
+    log: Logger,
+) -> io::Result<()> {
+    let listener = TcpListener::bind("0.0.0.0:9000")?;
+    info!(log, "admin API listening on {:?}", listener.local_addr());
+
+    let mut router = Router::new();
+    {
+        let workers = workers.clone();
+        router.get("/cluster/workers", move |_query| cluster_workers(&workers));
+    }
+    {
+        let workers = workers.clone();
+        let recipe_version = recipe_version.clone();
+        router.get("/cluster/status", move |_query| {
+            cluster_status(&workers, &recipe_version)
+        });
+    }
+    {
+        let workers = workers.clone();
+        let log = log.clone();
+        router.get("/cluster/drain", move |query| drain(&workers, &log, query));
+    }
+    {
+        let workers = workers.clone();
+        let recipe_version = recipe_version.clone();
+        let jobs = jobs.clone();
+        router.get("/metrics", move |_query| metrics(&workers, &recipe_version, &jobs));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(log, "admin API accept failed: {:?}", e);
+                continue;
+            }
+        };
+
+        if let Some((path, query)) = read_request_target(&stream) {
+            match router.dispatch(&path, &query) {
+                Some(body) => respond(stream, "200 OK", &body),
+                None => respond(stream, "404 Not Found", "not found\n"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Pull the request-line path and query string out of a raw HTTP/1.1
+/// request (e.g. `GET /cluster/drain?worker=1.2.3.4:5000 HTTP/1.1` ->
+/// `("/cluster/drain", "worker=1.2.3.4:5000")`). The admin API has no
+/// request bodies or headers worth parsing, so this is the entire "parser".
+fn read_request_target(mut stream: &TcpStream) -> Option<(String, String)> {
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).ok()?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let request_line = request.lines().next()?;
+    let target = request_line.split_whitespace().nth(1)?;
+    Some(match target.find('?') {
+        Some(idx) => (target[..idx].to_owned(), target[idx + 1..].to_owned()),
+        None => (target.to_owned(), String::new()),
+    })
+}
+
+/// Pull `key`'s value out of a `a=1&b=2`-style query string.
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let k = parts.next()?;
+            let v = parts.next()?;
+            if k == key { Some(v) } else { None }
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_param_finds_requested_key() {
+        assert_eq!(
+            query_param("worker=1.2.3.4:5000", "worker"),
+            Some("1.2.3.4:5000")
+        );
+    }
+
+    #[test]
+    fn query_param_finds_key_among_several() {
+        assert_eq!(query_param("a=1&worker=2.2.2.2:9&b=3", "worker"), Some("2.2.2.2:9"));
+    }
+
+    #[test]
+    fn query_param_missing_key_is_none() {
+        assert_eq!(query_param("a=1&b=2", "worker"), None);
+    }
+
+    #[test]
+    fn query_param_empty_query_is_none() {
+        assert_eq!(query_param("", "worker"), None);
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: text/plain\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+fn cluster_workers(workers: &Workers) -> String {
+    let workers = workers.lock().unwrap();
+    let mut out = String::new();
+    for (addr, ws) in workers.iter() {
+        out.push_str(&format!(
+            "{{\"addr\":\"{}\",\"state\":\"{}\",\"heartbeat_age_secs\":{:.3}}}\n",
+            addr,
+            ws.state().as_str(),
+            ws.heartbeat_age().as_secs() as f64 + f64::from(ws.heartbeat_age().subsec_nanos()) / 1e9
+        ));
+    }
+    out
+}
+
+/// Handle `GET /cluster/drain?worker=<addr>`: move `worker` from `Healthy`
+/// to `Draining` so the controller stops assigning it new domains. This is
+/// the operator-facing entry point for `Controller::request_drain`'s state
+/// transition -- the admin server runs on its own thread and only has the
+/// shared `workers` map, not a `Controller` handle, so it goes through
+/// `controller::drain_worker` directly instead.
+fn drain(workers: &Workers, log: &Logger, query: &str) -> String {
+    let addr = match query_param(query, "worker").and_then(|s| SocketAddr::from_str(s).ok()) {
+        Some(addr) => addr,
+        None => return "{\"error\":\"missing or invalid ?worker=<addr>\"}\n".to_owned(),
+    };
+
+    if controller::drain_worker(workers, log, &addr) {
+        format!("{{\"draining\":\"{}\"}}\n", addr)
+    } else {
+        format!("{{\"error\":\"{} is not a known, healthy worker\"}}\n", addr)
+    }
+}
+
+fn cluster_status(workers: &Workers, recipe_version: &Arc<Mutex<u64>>) -> String {
+    let workers = workers.lock().unwrap();
+    let version = *recipe_version.lock().unwrap();
+    format!(
+        "{{\"workers\":{},\"recipe_version\":{}}}\n",
+        workers.len(),
+        version
+    )
+}
+
+fn metrics(workers: &Workers, recipe_version: &Arc<Mutex<u64>>, jobs: &Arc<Mutex<JobQueue>>) -> String {
+    let workers = workers.lock().unwrap();
+    let healthy = workers.values().filter(|ws| ws.state().as_str() == "healthy").count();
+    let failed = workers.values().filter(|ws| ws.state().as_str() == "failed").count();
+    let version = *recipe_version.lock().unwrap();
+    let job_counts = jobs.lock().unwrap().counts();
+
+    let mut out = String::new();
+    out.push_str("# HELP noria_workers_registered Number of workers registered with the controller\n");
+    out.push_str("# TYPE noria_workers_registered gauge\n");
+    out.push_str(&format!("noria_workers_registered {}\n", workers.len()));
+
+    out.push_str("# HELP noria_workers_healthy Number of workers currently healthy\n");
+    out.push_str("# TYPE noria_workers_healthy gauge\n");
+    out.push_str(&format!("noria_workers_healthy {}\n", healthy));
+
+    out.push_str("# HELP noria_workers_failed Number of workers currently failed\n");
+    out.push_str("# TYPE noria_workers_failed gauge\n");
+    out.push_str(&format!("noria_workers_failed {}\n", failed));
+
+    out.push_str("# HELP noria_recipe_version Monotonic counter of recipe activations\n");
+    out.push_str("# TYPE noria_recipe_version counter\n");
+    out.push_str(&format!("noria_recipe_version {}\n", version));
+
+    out.push_str("# HELP noria_worker_heartbeat_age_seconds Seconds since a worker's last heartbeat\n");
+    out.push_str("# TYPE noria_worker_heartbeat_age_seconds gauge\n");
+    for (addr, ws) in workers.iter() {
+        out.push_str(&format!(
+            "noria_worker_heartbeat_age_seconds{{worker=\"{}\"}} {:.3}\n",
+            addr,
+            ws.heartbeat_age().as_secs() as f64 + f64::from(ws.heartbeat_age().subsec_nanos()) / 1e9
+        ));
+    }
+
+    out.push_str("# HELP noria_migrations_new Number of submitted migrations not yet dispatched\n");
+    out.push_str("# TYPE noria_migrations_new gauge\n");
+    out.push_str(&format!("noria_migrations_new {}\n", job_counts.new));
+
+    out.push_str("# HELP noria_migrations_running Number of migrations currently in flight\n");
+    out.push_str("# TYPE noria_migrations_running gauge\n");
+    out.push_str(&format!("noria_migrations_running {}\n", job_counts.running));
+
+    out.push_str("# HELP noria_migrations_done_total Total migrations completed successfully\n");
+    out.push_str("# TYPE noria_migrations_done_total counter\n");
+    out.push_str(&format!("noria_migrations_done_total {}\n", job_counts.done));
+
+    out.push_str("# HELP noria_migrations_failed_total Total migrations that failed\n");
+    out.push_str("# TYPE noria_migrations_failed_total counter\n");
+    out.push_str(&format!("noria_migrations_failed_total {}\n", job_counts.failed));
+
+    out
+}
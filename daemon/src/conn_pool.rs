@@ -0,0 +1,104 @@
+use channel::tcp::TcpSender;
+use distributary::CoordinationMessage;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// How many failed reconnect attempts -- accumulated one per health-check
+/// pass that finds the worker still `Suspect` -- a worker gets before the
+/// caller should give up and escalate it to `Failed` rather than keep
+/// treating the link as transient.
+pub const MAX_RECONNECT_ATTEMPTS: usize = 5;
+
+/// Tracks the in-flight reconnect state for a single worker's sender, so
+/// `check_worker_liveness` never has to block the poll loop waiting on a
+/// TCP connect. Each worker gets one of these; `try_reconnect` kicks off at
+/// most one background attempt at a time and folds its outcome back into
+/// `attempts` for the next health-check pass to read.
+pub struct ReconnectState {
+    attempts: AtomicUsize,
+    in_flight: AtomicBool,
+}
+
+impl ReconnectState {
+    pub fn new() -> Self {
+        ReconnectState {
+            attempts: AtomicUsize::new(0),
+            in_flight: AtomicBool::new(false),
+        }
+    }
+
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::SeqCst)
+    }
+}
+
+/// Kick off (at most) one background reconnect attempt to `remote` for the
+/// worker whose current sender is `sender`, tracked by `state`. A no-op if
+/// an attempt is already in flight, so repeated calls across health-check
+/// passes don't pile up connect attempts for an unreachable worker.
+///
+/// Non-blocking: the connect happens on a spawned thread, so a slow or
+/// unreachable worker can never stall the poll loop that calls this once
+/// per health-check pass. On success `sender` is swapped in place and
+/// `attempts` resets to 0; on failure `attempts` is bumped by exactly one,
+/// so the retry budget is spent across multiple heartbeat windows rather
+/// than exhausted in a single call.
+pub fn try_reconnect(
+    state: Arc<ReconnectState>,
+    sender: Arc<Mutex<TcpSender<CoordinationMessage>>>,
+    remote: SocketAddr,
+) {
+    if state.in_flight.compare_and_swap(false, true, Ordering::SeqCst) {
+        return;
+    }
+
+    thread::spawn(move || {
+        match TcpSender::connect(&remote, None) {
+            Ok(fresh) => {
+                *sender.lock().unwrap() = fresh;
+                state.attempts.store(0, Ordering::SeqCst);
+            }
+            Err(_) => {
+                state.attempts.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        state.in_flight.store(false, Ordering::SeqCst);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `try_reconnect`'s actual connect attempt needs a live peer speaking
+    // the channel wire protocol, so it isn't exercised here; these cover
+    // the pure counter/flag state that `check_worker_liveness` reads back.
+
+    #[test]
+    fn new_state_has_no_attempts() {
+        let state = ReconnectState::new();
+        assert_eq!(state.attempts(), 0);
+    }
+
+    #[test]
+    fn attempts_accumulate_and_reset_like_a_failed_then_recovered_link() {
+        let state = ReconnectState::new();
+        state.attempts.fetch_add(1, Ordering::SeqCst);
+        state.attempts.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(state.attempts(), 2);
+
+        // Mirrors what `try_reconnect` does on a successful connect.
+        state.attempts.store(0, Ordering::SeqCst);
+        assert_eq!(state.attempts(), 0);
+    }
+
+    #[test]
+    fn in_flight_guard_rejects_a_second_concurrent_attempt() {
+        let state = ReconnectState::new();
+        assert!(!state.in_flight.compare_and_swap(false, true, Ordering::SeqCst));
+        // A second caller finds it already in flight and must back off.
+        assert!(state.in_flight.compare_and_swap(false, true, Ordering::SeqCst));
+    }
+}
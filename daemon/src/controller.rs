@@ -7,22 +7,116 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use uuid::Uuid;
 
+use admin;
 use api;
+use conn_pool;
+use job_queue::JobQueue;
+
+/// A worker's position in its membership lifecycle.
+///
+/// `Healthy` and `Suspect` alternate with heartbeats; two consecutive missed
+/// windows move a worker to `Failed`. `Draining`/`Decommissioned` are the
+/// only way out of the cluster that doesn't imply data loss: an operator
+/// requests a drain, new domains stop being assigned, and once no job is
+/// still in flight for that worker it is retired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkerState {
+    Joining,
+    Healthy,
+    Suspect,
+    Failed,
+    Draining,
+    Decommissioned,
+}
 
 pub struct WorkerStatus {
-    healthy: bool,
+    state: WorkerState,
     last_heartbeat: Instant,
     sender: Option<Arc<Mutex<TcpSender<CoordinationMessage>>>>,
+    /// The worker's real listen address, as given at `Register` time. Used
+    /// to reconnect `sender` if the link breaks -- *not* the `workers` map
+    /// key, which is the address a message was received *from* and may be
+    /// an ephemeral outbound port rather than something reachable.
+    remote: SocketAddr,
+    /// Tracks reconnect attempts accumulated since the sender last worked,
+    /// one per health-check pass, off the poll-loop thread. Resets on a
+    /// successful reconnect so a flapping-then-recovering link doesn't
+    /// carry a grudge into the next outage.
+    reconnect: Arc<conn_pool::ReconnectState>,
 }
 
 impl WorkerStatus {
-    pub fn new(sender: Arc<Mutex<TcpSender<CoordinationMessage>>>) -> Self {
+    pub fn new(sender: Arc<Mutex<TcpSender<CoordinationMessage>>>, remote: SocketAddr) -> Self {
         WorkerStatus {
-            healthy: true,
+            state: WorkerState::Joining,
             last_heartbeat: Instant::now(),
             sender: Some(sender),
+            remote: remote,
+            reconnect: Arc::new(conn_pool::ReconnectState::new()),
+        }
+    }
+
+    pub fn state(&self) -> WorkerState {
+        self.state
+    }
+
+    pub fn heartbeat_age(&self) -> Duration {
+        self.last_heartbeat.elapsed()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.state == WorkerState::Healthy
+    }
+
+    /// Move a `Healthy` worker into `Draining`. Returns `false` (a no-op)
+    /// if the worker isn't currently `Healthy`. Exposed so both
+    /// `Controller::request_drain` and the admin API's drain endpoint --
+    /// which only has the shared `Workers` map, not a `Controller` -- share
+    /// one transition.
+    fn begin_drain(&mut self) -> bool {
+        if self.state == WorkerState::Healthy {
+            self.state = WorkerState::Draining;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl WorkerState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            WorkerState::Joining => "joining",
+            WorkerState::Healthy => "healthy",
+            WorkerState::Suspect => "suspect",
+            WorkerState::Failed => "failed",
+            WorkerState::Draining => "draining",
+            WorkerState::Decommissioned => "decommissioned",
+        }
+    }
+}
+
+/// The shared worker map, for code (like the admin HTTP API) that needs to
+/// flip a worker's lifecycle state without going through a `Controller`.
+pub type Workers = Arc<Mutex<HashMap<SocketAddr, WorkerStatus>>>;
+
+/// Move `addr` from `Healthy` to `Draining`, logging the transition.
+/// Returns `false` if `addr` isn't a known, currently `Healthy` worker.
+/// Called by `Controller::request_drain` and by the admin API's
+/// `/cluster/drain` endpoint, which runs on its own thread and only holds
+/// this shared map.
+pub fn drain_worker(workers: &Workers, log: &Logger, addr: &SocketAddr) -> bool {
+    match workers.lock().unwrap().get_mut(addr) {
+        Some(ws) => {
+            let began = ws.begin_drain();
+            if began {
+                info!(log, "draining worker at {:?}", addr);
+            }
+            began
         }
+        None => false,
     }
 }
 
@@ -33,7 +127,13 @@ pub struct Controller {
     log: Logger,
 
     blender: Arc<Mutex<Blender>>,
-    workers: HashMap<SocketAddr, WorkerStatus>,
+    workers: Arc<Mutex<HashMap<SocketAddr, WorkerStatus>>>,
+    jobs: Arc<Mutex<JobQueue>>,
+
+    /// Bumped every time a worker reports a recipe job complete (see
+    /// `handle_job_complete`), so the admin API can report it without the
+    /// `Controller` having to hand out the `Recipe` itself.
+    recipe_version: Arc<Mutex<u64>>,
 
     heartbeat_every: Duration,
     healthcheck_every: Duration,
@@ -56,7 +156,9 @@ impl Controller {
             listen_port: port,
             log: log,
             blender: Arc::new(Mutex::new(blender)),
-            workers: HashMap::new(),
+            workers: Arc::new(Mutex::new(HashMap::new())),
+            jobs: Arc::new(Mutex::new(JobQueue::new())),
+            recipe_version: Arc::new(Mutex::new(0)),
             heartbeat_every: heartbeat_every,
             healthcheck_every: healthcheck_every,
             last_checked_workers: Instant::now(),
@@ -83,6 +185,19 @@ impl Controller {
             Err(e) => panic!("failed to spawn API server: {:?}", e),
         };
 
+        // run the admin API (cluster status + Prometheus metrics)
+        let admin_tb = thread::Builder::new().name("admin-srv".into());
+        let admin_workers = self.workers.clone();
+        let admin_recipe_version = self.recipe_version.clone();
+        let admin_jobs = self.jobs.clone();
+        let admin_logger = self.log.clone();
+        let admin_jh = match admin_tb.spawn(move || {
+            admin::run(admin_workers, admin_recipe_version, admin_jobs, admin_logger).unwrap()
+        }) {
+            Ok(jh) => jh,
+            Err(e) => panic!("failed to spawn admin server: {:?}", e),
+        };
+
         let mut pl: PollingLoop<CoordinationMessage> = PollingLoop::from_listener(listener);
         pl.run_polling_loop(|e| {
             match e {
@@ -103,28 +218,219 @@ impl Controller {
         });
 
         api_jh.join().unwrap();
+        admin_jh.join().unwrap();
     }
 
     fn check_worker_liveness(&mut self) {
         if self.last_checked_workers.elapsed() > self.healthcheck_every {
-            for (addr, ws) in self.workers.iter_mut() {
-                if ws.healthy && ws.last_heartbeat.elapsed() > self.heartbeat_every * 3 {
-                    warn!(self.log, "worker at {:?} has failed!", addr);
-                    ws.healthy = false;
+            let mut workers = self.workers.lock().unwrap();
+            let mut failed = Vec::new();
+            for (addr, ws) in workers.iter_mut() {
+                let elapsed = ws.last_heartbeat.elapsed();
+                match ws.state {
+                    WorkerState::Healthy if elapsed > self.heartbeat_every * 3 => {
+                        warn!(self.log, "worker at {:?} missed a heartbeat, marking suspect", addr);
+                        ws.state = WorkerState::Suspect;
+                    }
+                    WorkerState::Suspect => {
+                        conn_pool::try_reconnect(
+                            ws.reconnect.clone(),
+                            ws.sender.as_ref().unwrap().clone(),
+                            ws.remote,
+                        );
+
+                        let gave_up_reconnecting =
+                            ws.reconnect.attempts() >= conn_pool::MAX_RECONNECT_ATTEMPTS;
+                        if gave_up_reconnecting || elapsed > self.heartbeat_every * 6 {
+                            warn!(self.log, "worker at {:?} has failed!", addr);
+                            ws.state = WorkerState::Failed;
+                            failed.push(*addr);
+                        }
+                    }
+                    WorkerState::Draining if self.jobs.lock().unwrap().jobs_for_worker(addr).is_empty() => {
+                        info!(self.log, "worker at {:?} has quiesced, decommissioning", addr);
+                        ws.state = WorkerState::Decommissioned;
+                    }
+                    _ => {}
                 }
             }
+            let healthy: Vec<SocketAddr> = workers
+                .iter()
+                .filter(|&(_, ws)| ws.is_healthy())
+                .map(|(addr, _)| *addr)
+                .collect();
+            drop(workers);
+
+            for addr in failed {
+                self.failover_worker(&addr, &healthy);
+            }
+
+            // Any job whose heartbeat is stale is reset to `New` regardless of
+            // whether its worker was otherwise marked unhealthy above -- a
+            // worker can be alive but wedged on a single job.
+            let orphaned = self.jobs.lock().unwrap().reap_stale(self.heartbeat_every * 3);
+            for id in orphaned {
+                warn!(self.log, "job {} timed out, returning to queue", id);
+                self.dispatch_job(id);
+            }
+
+            // `reap_stale` only retries a job that made it to `Running` and
+            // then went quiet -- a job that never got off the ground (no
+            // healthy worker at submission time, or its initial send failed)
+            // stays `New` forever unless something else retries it. Give
+            // every still-`New` job another dispatch attempt each pass;
+            // `dispatch_job` is a no-op if there's still nowhere to send it.
+            let pending = self.jobs.lock().unwrap().new_job_ids();
+            for id in pending {
+                self.dispatch_job(id);
+            }
+
             self.last_checked_workers = Instant::now();
         }
     }
 
+    /// Offer a `New` job to some healthy worker by sending it a `JobAssign`
+    /// message over that worker's connection. A no-op if no healthy worker
+    /// is currently registered, or if the job is no longer known; either
+    /// way the job simply stays `New` until the next dispatch attempt. The
+    /// job is only ever moved to `Running` by the worker claiming it back
+    /// with a `JobClaim` message (see `handle_job_claim`) -- the controller
+    /// never marks a job claimed on a worker's behalf.
+    fn dispatch_job(&mut self, id: Uuid) {
+        let recipe = match self.jobs.lock().unwrap().recipe_for(id) {
+            Some(recipe) => recipe,
+            None => return,
+        };
+
+        let target = self.workers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|&(_, ws)| ws.is_healthy())
+            .map(|(addr, ws)| (*addr, ws.sender.clone()));
+
+        let (addr, sender) = match target {
+            Some((addr, Some(sender))) => (addr, sender),
+            _ => return,
+        };
+
+        let msg = CoordinationMessage {
+            source: self.my_addr(),
+            payload: CoordinationPayload::JobAssign(id, recipe),
+        };
+        if let Err(e) = sender.lock().unwrap().send(msg) {
+            warn!(self.log, "failed to dispatch job {} to {:?}: {:?}", id, addr, e);
+        }
+    }
+
+    /// Submit a recipe activation as a durable job and immediately try to
+    /// dispatch it, so an in-flight migration survives the worker executing
+    /// it crashing partway through -- the reaper in `check_worker_liveness`
+    /// will re-dispatch it to another worker once its heartbeat goes stale.
+    /// `api::run` is spawned with only the `Blender` handle and does not go
+    /// through this method: recipes it receives are not recorded as jobs.
+    pub fn submit_recipe(&mut self, recipe: String) -> Uuid {
+        let id = self.jobs.lock().unwrap().submit(recipe);
+        self.dispatch_job(id);
+        id
+    }
+
+    /// This controller's own address, as workers see it -- used as the
+    /// `source` of messages the controller originates itself (as opposed to
+    /// ones it's relaying or replying to).
+    fn my_addr(&self) -> SocketAddr {
+        use std::str::FromStr;
+        SocketAddr::from_str(&format!("{}:{}", self.listen_addr, self.listen_port)).unwrap()
+    }
+
+    /// Re-materialize whatever domains `dead` was hosting onto the remaining
+    /// healthy workers. Runs once, right after a worker is declared `Failed`.
+    /// Unlike a recipe migration, the rebalance itself is carried out by the
+    /// controller (via `Blender`), not dispatched to and claimed by a
+    /// remote worker -- so it is submitted to the job queue as a job the
+    /// controller itself starts and resolves, purely so it shows up
+    /// alongside other in-flight jobs in the `/metrics` job counts and can be
+    /// reaped like any other job if the controller wedges mid-rebalance.
+    fn failover_worker(&mut self, dead: &SocketAddr, healthy: &[SocketAddr]) {
+        if healthy.is_empty() {
+            crit!(
+                self.log,
+                "worker {:?} failed but no healthy workers remain to take over its domains",
+                dead
+            );
+            return;
+        }
+
+        let mut b = self.blender.lock().unwrap();
+        let orphaned = b.remove_worker(dead);
+        if orphaned.is_empty() {
+            return;
+        }
+
+        warn!(
+            self.log,
+            "reassigning {} domain(s) from failed worker {:?} to {} healthy worker(s)",
+            orphaned.len(),
+            dead,
+            healthy.len()
+        );
+
+        let job_id = self.jobs.lock().unwrap().submit(format!("reassign-domains-from-{}", dead));
+        self.jobs.lock().unwrap().start(job_id);
+
+        match b.reassign_domains(orphaned, healthy) {
+            Ok(()) => self.jobs.lock().unwrap().complete(job_id),
+            Err(e) => {
+                error!(self.log, "failed to reassign domains from {:?}: {:?}", dead, e);
+                self.jobs.lock().unwrap().fail(job_id);
+            }
+        }
+    }
+
+    /// Begin gracefully decommissioning a worker: stop assigning it new
+    /// domains and wait for its in-flight jobs to quiesce before retiring it
+    /// in `check_worker_liveness`. Returns `false` if the worker isn't
+    /// currently `Healthy`.
+    pub fn request_drain(&mut self, addr: &SocketAddr) -> bool {
+        drain_worker(&self.workers, &self.log, addr)
+    }
+
     fn handle(&mut self, msg: &CoordinationMessage) -> Result<(), io::Error> {
         match msg.payload {
             CoordinationPayload::Register(ref remote) => self.handle_register(msg, remote),
             CoordinationPayload::Heartbeat => self.handle_heartbeat(msg),
+            CoordinationPayload::JobClaim(id) => self.handle_job_claim(msg, id),
+            CoordinationPayload::JobComplete(id) => self.handle_job_complete(msg, id),
             _ => unimplemented!(),
         }
     }
 
+    fn handle_job_claim(&mut self, msg: &CoordinationMessage, id: Uuid) -> Result<(), io::Error> {
+        if self.jobs.lock().unwrap().claim(id, msg.source) {
+            info!(self.log, "worker {:?} claimed job {}", msg.source, id);
+        } else {
+            warn!(
+                self.log,
+                "worker {:?} tried to claim job {} but it was not New",
+                msg.source,
+                id
+            );
+        }
+
+        Ok(())
+    }
+
+    fn handle_job_complete(
+        &mut self,
+        msg: &CoordinationMessage,
+        id: Uuid,
+    ) -> Result<(), io::Error> {
+        info!(self.log, "worker {:?} completed job {}", msg.source, id);
+        self.jobs.lock().unwrap().complete(id);
+        *self.recipe_version.lock().unwrap() += 1;
+        Ok(())
+    }
+
     fn handle_register(
         &mut self,
         msg: &CoordinationMessage,
@@ -138,8 +444,8 @@ impl Controller {
         );
 
         let sender = Arc::new(Mutex::new(TcpSender::connect(remote, None)?));
-        let ws = WorkerStatus::new(sender.clone());
-        self.workers.insert(msg.source.clone(), ws);
+        let ws = WorkerStatus::new(sender.clone(), *remote);
+        self.workers.lock().unwrap().insert(msg.source.clone(), ws);
 
         let mut b = self.blender.lock().unwrap();
         b.add_worker(msg.source, sender);
@@ -148,7 +454,7 @@ impl Controller {
     }
 
     fn handle_heartbeat(&mut self, msg: &CoordinationMessage) -> Result<(), io::Error> {
-        match self.workers.get_mut(&msg.source) {
+        match self.workers.lock().unwrap().get_mut(&msg.source) {
             None => {
                 crit!(
                     self.log,
@@ -157,10 +463,36 @@ impl Controller {
                 )
             }
             Some(ref mut ws) => {
+                match ws.state {
+                    WorkerState::Suspect => {
+                        info!(self.log, "worker at {:?} recovered", msg.source);
+                        ws.state = WorkerState::Healthy;
+                    }
+                    WorkerState::Joining => {
+                        info!(self.log, "worker at {:?} finished joining", msg.source);
+                        ws.state = WorkerState::Healthy;
+                    }
+                    _ => {}
+                }
                 ws.last_heartbeat = Instant::now();
             }
         }
 
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_state_as_str_covers_every_variant() {
+        assert_eq!(WorkerState::Joining.as_str(), "joining");
+        assert_eq!(WorkerState::Healthy.as_str(), "healthy");
+        assert_eq!(WorkerState::Suspect.as_str(), "suspect");
+        assert_eq!(WorkerState::Failed.as_str(), "failed");
+        assert_eq!(WorkerState::Draining.as_str(), "draining");
+        assert_eq!(WorkerState::Decommissioned.as_str(), "decommissioned");
+    }
 }
\ No newline at end of file
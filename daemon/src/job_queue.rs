@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use uuid::Uuid;
+
+/// Lifecycle of a single migration job tracked by the `Controller`.
+///
+/// A job starts life as `New`, is claimed by exactly one worker (`Running`),
+/// and ends in `Done` or `Failed`. The reaper in `Controller::check_worker_liveness`
+/// is the only thing allowed to move a job back from `Running` to `New`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    New,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A durable record of a recipe activation / migration.
+///
+/// `recipe` holds the serialized payload so a job can be re-dispatched to a
+/// different worker without going back to whatever originally submitted it.
+pub struct Job {
+    pub id: Uuid,
+    pub recipe: String,
+    pub status: JobStatus,
+    pub assigned_worker: Option<SocketAddr>,
+    pub last_heartbeat: Instant,
+}
+
+impl Job {
+    fn new(recipe: String) -> Self {
+        Job {
+            id: Uuid::new_v4(),
+            recipe: recipe,
+            status: JobStatus::New,
+            assigned_worker: None,
+            last_heartbeat: Instant::now(),
+        }
+    }
+}
+
+/// Tracks all migration jobs the `Controller` has ever submitted, so that a
+/// worker crash between claim and completion can be recovered purely from
+/// heartbeat timeouts rather than requiring the original caller to retry.
+pub struct JobQueue {
+    jobs: HashMap<Uuid, Job>,
+}
+
+/// A snapshot count of jobs by status, returned by `JobQueue::counts`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JobCounts {
+    pub new: usize,
+    pub running: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        JobQueue { jobs: HashMap::new() }
+    }
+
+    /// Enqueue a new migration job in the `New` state.
+    pub fn submit(&mut self, recipe: String) -> Uuid {
+        let job = Job::new(recipe);
+        let id = job.id;
+        self.jobs.insert(id, job);
+        id
+    }
+
+    /// Atomically transition a `New` job to `Running`, assigning it to
+    /// `worker` and stamping its heartbeat. Returns `false` if the job is
+    /// unknown or was not in `New` (e.g. another worker already claimed it).
+    pub fn claim(&mut self, id: Uuid, worker: SocketAddr) -> bool {
+        match self.jobs.get_mut(&id) {
+            Some(job) if job.status == JobStatus::New => {
+                job.status = JobStatus::Running;
+                job.assigned_worker = Some(worker);
+                job.last_heartbeat = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Like `claim`, but for work the controller itself carries out
+    /// in-process (e.g. a domain rebalance) rather than dispatching to a
+    /// remote worker. Leaves `assigned_worker` unset since no worker ever
+    /// claims this kind of job over the wire.
+    pub fn start(&mut self, id: Uuid) -> bool {
+        match self.jobs.get_mut(&id) {
+            Some(job) if job.status == JobStatus::New => {
+                job.status = JobStatus::Running;
+                job.last_heartbeat = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The recipe payload for `id`, if the job is still known. Used to
+    /// re-dispatch a job without the caller having to hang on to the
+    /// original recipe string itself.
+    pub fn recipe_for(&self, id: Uuid) -> Option<String> {
+        self.jobs.get(&id).map(|job| job.recipe.clone())
+    }
+
+    /// Refresh the heartbeat for a job the caller still holds `Running`.
+    pub fn heartbeat(&mut self, id: Uuid) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            if job.status == JobStatus::Running {
+                job.last_heartbeat = Instant::now();
+            }
+        }
+    }
+
+    pub fn complete(&mut self, id: Uuid) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.status = JobStatus::Done;
+        }
+    }
+
+    pub fn fail(&mut self, id: Uuid) {
+        if let Some(job) = self.jobs.get_mut(&id) {
+            job.status = JobStatus::Failed;
+        }
+    }
+
+    /// Reset any `Running` job whose heartbeat is older than `timeout` back
+    /// to `New` so it can be re-dispatched to a different, live worker.
+    /// Returns the ids of jobs that were reset.
+    pub fn reap_stale(&mut self, timeout: ::std::time::Duration) -> Vec<Uuid> {
+        let mut reset = Vec::new();
+        for (id, job) in self.jobs.iter_mut() {
+            if job.status == JobStatus::Running && job.last_heartbeat.elapsed() > timeout {
+                job.status = JobStatus::New;
+                job.assigned_worker = None;
+                reset.push(*id);
+            }
+        }
+        reset
+    }
+
+    /// Every job still `Running` on `worker`. Used by `check_worker_liveness`
+    /// to tell whether a `Draining` worker has quiesced yet -- a job that's
+    /// already `Done`/`Failed` keeps its `assigned_worker` around for
+    /// history, so this must filter on status rather than just the
+    /// assignment, or a worker that ever completed a single job would never
+    /// be considered quiesced.
+    pub fn jobs_for_worker(&self, worker: &SocketAddr) -> Vec<Uuid> {
+        self.jobs
+            .iter()
+            .filter(|&(_, job)| {
+                job.status == JobStatus::Running && job.assigned_worker.as_ref() == Some(worker)
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// A snapshot count of jobs in each status, for the admin API's
+    /// Prometheus `/metrics` endpoint to report migration counts without
+    /// handing out the jobs themselves.
+    pub fn counts(&self) -> JobCounts {
+        let mut counts = JobCounts::default();
+        for job in self.jobs.values() {
+            match job.status {
+                JobStatus::New => counts.new += 1,
+                JobStatus::Running => counts.running += 1,
+                JobStatus::Done => counts.done += 1,
+                JobStatus::Failed => counts.failed += 1,
+            }
+        }
+        counts
+    }
+
+    /// Every job still `New`. Unlike `reap_stale`, which only retries a job
+    /// that made it to `Running` and then went quiet, this catches a job
+    /// that never got off the ground at all -- e.g. it was submitted (or
+    /// reset) while no healthy worker was registered, or its initial
+    /// dispatch send failed -- so `check_worker_liveness` can give it
+    /// another dispatch attempt on every health-check pass.
+    pub fn new_job_ids(&self) -> Vec<Uuid> {
+        self.jobs
+            .iter()
+            .filter(|&(_, job)| job.status == JobStatus::New)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn claim_moves_new_to_running_and_assigns_worker() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        assert!(q.claim(id, addr(8000)));
+        assert_eq!(q.jobs.get(&id).unwrap().status, JobStatus::Running);
+        assert_eq!(q.jobs.get(&id).unwrap().assigned_worker, Some(addr(8000)));
+    }
+
+    #[test]
+    fn claim_is_not_reentrant() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        assert!(q.claim(id, addr(8000)));
+        assert!(!q.claim(id, addr(8001)));
+        assert_eq!(q.jobs.get(&id).unwrap().assigned_worker, Some(addr(8000)));
+    }
+
+    #[test]
+    fn claim_of_unknown_job_fails() {
+        let mut q = JobQueue::new();
+        assert!(!q.claim(Uuid::new_v4(), addr(8000)));
+    }
+
+    #[test]
+    fn new_job_ids_only_returns_new_jobs() {
+        let mut q = JobQueue::new();
+        let new_id = q.submit("recipe".to_owned());
+        let running_id = q.submit("recipe".to_owned());
+        q.claim(running_id, addr(8000));
+
+        assert_eq!(q.new_job_ids(), vec![new_id]);
+    }
+
+    #[test]
+    fn reap_stale_resets_running_jobs_past_timeout() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        q.claim(id, addr(8000));
+        q.jobs.get_mut(&id).unwrap().last_heartbeat =
+            Instant::now() - ::std::time::Duration::from_secs(60);
+
+        let reset = q.reap_stale(::std::time::Duration::from_secs(1));
+
+        assert_eq!(reset, vec![id]);
+        assert_eq!(q.jobs.get(&id).unwrap().status, JobStatus::New);
+        assert_eq!(q.jobs.get(&id).unwrap().assigned_worker, None);
+    }
+
+    #[test]
+    fn reap_stale_leaves_fresh_running_jobs_alone() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        q.claim(id, addr(8000));
+
+        let reset = q.reap_stale(::std::time::Duration::from_secs(60));
+
+        assert!(reset.is_empty());
+        assert_eq!(q.jobs.get(&id).unwrap().status, JobStatus::Running);
+    }
+
+    #[test]
+    fn jobs_for_worker_ignores_completed_jobs() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        q.claim(id, addr(8000));
+        q.complete(id);
+
+        assert!(q.jobs_for_worker(&addr(8000)).is_empty());
+    }
+
+    #[test]
+    fn jobs_for_worker_ignores_failed_jobs() {
+        let mut q = JobQueue::new();
+        let id = q.submit("recipe".to_owned());
+        q.claim(id, addr(8000));
+        q.fail(id);
+
+        assert!(q.jobs_for_worker(&addr(8000)).is_empty());
+    }
+
+    #[test]
+    fn jobs_for_worker_only_returns_that_workers_running_jobs() {
+        let mut q = JobQueue::new();
+        let mine = q.submit("recipe".to_owned());
+        let theirs = q.submit("recipe".to_owned());
+        q.claim(mine, addr(8000));
+        q.claim(theirs, addr(8001));
+
+        assert_eq!(q.jobs_for_worker(&addr(8000)), vec![mine]);
+    }
+
+    #[test]
+    fn counts_reflect_every_status() {
+        let mut q = JobQueue::new();
+        q.submit("recipe".to_owned());
+        let running_id = q.submit("recipe".to_owned());
+        let done_id = q.submit("recipe".to_owned());
+        let failed_id = q.submit("recipe".to_owned());
+
+        q.claim(running_id, addr(8000));
+        q.claim(done_id, addr(8000));
+        q.complete(done_id);
+        q.claim(failed_id, addr(8000));
+        q.fail(failed_id);
+
+        assert_eq!(
+            q.counts(),
+            JobCounts {
+                new: 1,
+                running: 1,
+                done: 1,
+                failed: 1,
+            }
+        );
+    }
+}
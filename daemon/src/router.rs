@@ -0,0 +1,33 @@
+/// Minimal GET-only path dispatch for the admin HTTP server.
+///
+/// The admin API has a handful of fixed routes, so a linear scan over a
+/// small `Vec` is simpler than pulling in a full routing crate for what the
+/// API server already does on its own. Handlers receive the request's raw
+/// query string (everything after `?`, or `""` if there wasn't one) so a
+/// route like `/cluster/drain` can take a `?worker=` parameter without the
+/// router needing to know about path parameters.
+pub struct Router {
+    routes: Vec<(&'static str, Box<Fn(&str) -> String + Send + Sync>)>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router { routes: Vec::new() }
+    }
+
+    pub fn get<F>(&mut self, path: &'static str, handler: F)
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.routes.push((path, Box::new(handler)));
+    }
+
+    /// Returns the handler's response body for `path`, or `None` if no
+    /// route matches (the caller should answer with a 404).
+    pub fn dispatch(&self, path: &str, query: &str) -> Option<String> {
+        self.routes
+            .iter()
+            .find(|&&(route, _)| route == path)
+            .map(|&(_, ref handler)| handler(query))
+    }
+}
@@ -4,11 +4,19 @@ extern crate slog;
 #[macro_use]
 extern crate serde_derive;
 extern crate toml;
+extern crate chrono;
 extern crate distributary;
 extern crate mysql;
+extern crate postgres;
+extern crate r2d2;
+extern crate r2d2_postgres;
 
+use chrono::NaiveDateTime;
 use mysql::OptsBuilder;
-use mysql::value::Params;
+use mysql::Value as MysqlValue;
+use mysql::value::Params as MysqlParams;
+use postgres::types::ToSql;
+use r2d2_postgres::{PostgresConnectionManager, TlsMode};
 
 use std::path::Path;
 use std::io::{Read, Write};
@@ -27,10 +35,19 @@ use distributary::{Blender, Recipe, DataType};
 
 const DIRECTORY_PREFIX: &str = "tests/mysql_comparison_tests";
 
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
 #[derive(Debug, Deserialize)]
 enum Type {
     Int,
     Text,
+    Real,
+    Timestamp,
+    /// Wraps another `Type` whose column may hold `NULL`. The literal
+    /// `"NULL"` (case-sensitive, matching how it's written in schema TOML
+    /// and target files) is treated as the null marker rather than being
+    /// handed to the inner type.
+    Nullable(Box<Type>),
 }
 
 impl Type {
@@ -38,6 +55,19 @@ impl Type {
         match *self {
             Type::Int => i64::from_str(value).unwrap().into(),
             Type::Text => value.into(),
+            Type::Real => f64::from_str(value).unwrap().into(),
+            Type::Timestamp => {
+                NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT)
+                    .unwrap()
+                    .into()
+            }
+            Type::Nullable(ref inner) => {
+                if value == "NULL" {
+                    DataType::None
+                } else {
+                    inner.make_datatype(value)
+                }
+            }
         }
     }
 }
@@ -63,29 +93,91 @@ struct Schema {
     queries: BTreeMap<String, Query>,
 }
 
-fn read_file<P: AsRef<Path>>(file_name: P) -> String {
-    let mut contents = String::new();
-    let mut file = File::open(file_name).unwrap();
-    file.read_to_string(&mut contents).unwrap();
-    contents
+/// One oracle database the differential tester can run a schema against.
+/// Each backend owns its own notion of placeholders and literal quoting, so
+/// a single schema (written with `?` placeholders) can be replayed against
+/// every backend without the harness caring which SQL dialect it's talking.
+trait Backend {
+    fn name(&self) -> &'static str;
+
+    /// The placeholder a backend expects for the `index`'th bound parameter
+    /// (`?` for MySQL, `$1`-style for Postgres).
+    fn placeholder(&self, index: usize) -> String;
+
+    /// How `value` should be written as a literal of type `ty` when it's
+    /// spliced directly into an `INSERT` statement.
+    fn quote(&self, ty: &Type, value: &str) -> String;
+
+    fn run_ddl(&self, ddl: &str);
+    fn insert_row(&self, table_name: &str, types: &[Type], row: &[String]);
+    fn select(&self, query: &str, types: &[Type], params: &[String]) -> Vec<Vec<String>>;
 }
 
-fn write_file<P: AsRef<Path>>(file_name: P, contents: String) {
-    let mut file = File::create(file_name).unwrap();
-    file.write_all(contents.as_bytes()).unwrap();
+/// Rewrite a schema's `?`-style placeholders into whatever `backend` expects.
+fn rewrite_placeholders(query: &str, backend: &Backend) -> String {
+    let mut out = String::new();
+    let mut index = 0;
+    for ch in query.chars() {
+        if ch == '?' {
+            out.push_str(&backend.placeholder(index));
+            index += 1;
+        } else {
+            out.push(ch);
+        }
+    }
+    out
 }
 
-fn run_for_all_in_directory<F: FnMut(String, String)>(directory: &str, mut f: F) {
-    let directory = Path::new(DIRECTORY_PREFIX).join(directory);
-    for entry in fs::read_dir(directory).unwrap() {
-        let entry = entry.unwrap();
-        f(
-            entry.file_name().to_str().unwrap().to_owned(),
-            read_file(entry.path().to_str().unwrap()),
-        );
+/// Box a single query parameter as its typed `ToSql` representation, for
+/// backends (like Postgres) that bind parameters instead of splicing them
+/// into the query text as literals.
+fn bind_param(ty: &Type, value: &str) -> Box<ToSql> {
+    match *ty {
+        Type::Int => Box::new(i64::from_str(value).unwrap()) as Box<ToSql>,
+        Type::Text => Box::new(value.to_owned()) as Box<ToSql>,
+        Type::Real => Box::new(f64::from_str(value).unwrap()) as Box<ToSql>,
+        Type::Timestamp => {
+            Box::new(NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).unwrap()) as
+                Box<ToSql>
+        }
+        Type::Nullable(ref inner) => {
+            if value == "NULL" {
+                Box::new(None::<String>) as Box<ToSql>
+            } else {
+                bind_param(inner, value)
+            }
+        }
     }
 }
 
+/// Bind a single query parameter as its typed `mysql::Value`, mirroring
+/// `bind_param` for backends (like MySQL) that take values instead of
+/// `ToSql` trait objects. In particular, a `Nullable` parameter whose value
+/// is the sentinel string `"NULL"` must become `Value::NULL`, not the
+/// 4-character string -- otherwise a query comparing a nullable column
+/// against a NULL parameter would diverge from Postgres/Noria.
+fn bind_mysql_param(ty: &Type, value: &str) -> MysqlValue {
+    match *ty {
+        Type::Int => MysqlValue::from(i64::from_str(value).unwrap()),
+        Type::Text => MysqlValue::from(value),
+        Type::Real => MysqlValue::from(f64::from_str(value).unwrap()),
+        Type::Timestamp => MysqlValue::from(
+            NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT).unwrap(),
+        ),
+        Type::Nullable(ref inner) => {
+            if value == "NULL" {
+                MysqlValue::NULL
+            } else {
+                bind_mysql_param(inner, value)
+            }
+        }
+    }
+}
+
+pub struct MysqlBackend {
+    pool: mysql::Pool,
+}
+
 pub fn setup_mysql(addr: &str) -> mysql::Pool {
     use mysql::Opts;
 
@@ -116,60 +208,248 @@ pub fn setup_mysql(addr: &str) -> mysql::Pool {
     mysql::Pool::new_manual(1, 4, opts).unwrap()
 }
 
-fn generate_target_results(schemas: &BTreeMap<String, Schema>) {
+impl MysqlBackend {
+    pub fn new(addr: &str) -> Self {
+        MysqlBackend { pool: setup_mysql(addr) }
+    }
+}
+
+impl Backend for MysqlBackend {
+    fn name(&self) -> &'static str {
+        "mysql"
+    }
+
+    fn placeholder(&self, _index: usize) -> String {
+        "?".to_owned()
+    }
+
+    fn quote(&self, ty: &Type, value: &str) -> String {
+        match *ty {
+            Type::Text | Type::Timestamp => format!("\"{}\"", value),
+            Type::Int | Type::Real => value.to_owned(),
+            Type::Nullable(ref inner) => {
+                if value == "NULL" {
+                    "NULL".to_owned()
+                } else {
+                    self.quote(inner, value)
+                }
+            }
+        }
+    }
+
+    fn run_ddl(&self, ddl: &str) {
+        self.pool.prep_exec(ddl, ()).unwrap();
+    }
+
+    fn insert_row(&self, table_name: &str, types: &[Type], row: &[String]) {
+        let values: Vec<_> = row.iter()
+            .zip(types.iter())
+            .map(|(v, t)| self.quote(t, v))
+            .collect();
+        let insert_query = format!("INSERT INTO {} VALUES ({})", table_name, values.join(", "));
+        self.pool.prep_exec(&insert_query, ()).unwrap();
+    }
+
+    fn select(&self, query: &str, types: &[Type], params: &[String]) -> Vec<Vec<String>> {
+        let query = rewrite_placeholders(query, self);
+        let values = MysqlParams::Positional(
+            params
+                .iter()
+                .zip(types.iter())
+                .map(|(v, t)| bind_mysql_param(t, v))
+                .collect(),
+        );
+        self.pool
+            .prep_exec(&query, values)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .unwrap()
+                    .into_iter()
+                    .map(|v| {
+                        v.into_str()
+                            .trim_matches(|c| c == '\'' || c == '"')
+                            .to_owned()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+pub struct PostgresBackend {
+    pool: r2d2::Pool<PostgresConnectionManager>,
+}
+
+pub fn setup_postgres(addr: &str) -> r2d2::Pool<PostgresConnectionManager> {
+    let addr = format!("postgres://{}", addr);
+    let slash = addr.rfind("/").unwrap();
+    let db = &addr[slash + 1..];
+    let base = &addr[0..slash];
+
+    // clear the db (connect against the default `postgres` database, since
+    // the target database is dropped and recreated here)
+    let admin = postgres::Connection::connect(
+        format!("{}/postgres", base),
+        postgres::TlsMode::None,
+    ).unwrap();
+    let _ = admin.execute(&format!("DROP DATABASE IF EXISTS {}", db), &[]);
+    admin.execute(&format!("CREATE DATABASE {}", db), &[]).unwrap();
+    drop(admin);
+
+    let manager = PostgresConnectionManager::new(addr.as_str(), TlsMode::None).unwrap();
+    r2d2::Pool::builder().max_size(4).build(manager).unwrap()
+}
+
+impl PostgresBackend {
+    pub fn new(addr: &str) -> Self {
+        PostgresBackend { pool: setup_postgres(addr) }
+    }
+}
+
+impl Backend for PostgresBackend {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn placeholder(&self, index: usize) -> String {
+        format!("${}", index + 1)
+    }
+
+    fn quote(&self, ty: &Type, value: &str) -> String {
+        match *ty {
+            Type::Text | Type::Timestamp => format!("'{}'", value),
+            Type::Int | Type::Real => value.to_owned(),
+            Type::Nullable(ref inner) => {
+                if value == "NULL" {
+                    "NULL".to_owned()
+                } else {
+                    self.quote(inner, value)
+                }
+            }
+        }
+    }
+
+    fn run_ddl(&self, ddl: &str) {
+        self.pool.get().unwrap().execute(ddl, &[]).unwrap();
+    }
+
+    fn insert_row(&self, table_name: &str, types: &[Type], row: &[String]) {
+        let values: Vec<_> = row.iter()
+            .zip(types.iter())
+            .map(|(v, t)| self.quote(t, v))
+            .collect();
+        let insert_query = format!("INSERT INTO {} VALUES ({})", table_name, values.join(", "));
+        self.pool.get().unwrap().execute(&insert_query, &[]).unwrap();
+    }
+
+    fn select(&self, query: &str, types: &[Type], params: &[String]) -> Vec<Vec<String>> {
+        let query = rewrite_placeholders(query, self);
+        let bound: Vec<Box<ToSql>> = params
+            .iter()
+            .zip(types.iter())
+            .map(|(v, t)| bind_param(t, v))
+            .collect();
+        let refs: Vec<&ToSql> = bound.iter().map(|b| b.as_ref()).collect();
+
+        let conn = self.pool.get().unwrap();
+        let rows = conn.query(&query, &refs).unwrap();
+        rows.iter()
+            .map(|row| (0..row.len()).map(|i| stringify_column(&row, i)).collect())
+            .collect()
+    }
+}
+
+/// Stringify one result column, trying each of the types `Type` can
+/// represent in turn. `Row::get_opt` resolves a `NULL` column to `Ok(None)`
+/// regardless of which type it's asked for, so the first type that doesn't
+/// error (a wrong-type mismatch) tells us both whether the value is `NULL`
+/// and, if not, how to format it -- mirroring the `DataType` match in
+/// `check_query` so a `Real`/`Timestamp`/`Nullable` column compares equal
+/// no matter which backend produced it.
+fn stringify_column(row: &postgres::rows::Row, i: usize) -> String {
+    if let Ok(opt) = row.get_opt::<_, String>(i) {
+        return opt.unwrap_or_else(|| "NULL".to_owned());
+    }
+    if let Ok(opt) = row.get_opt::<_, i64>(i) {
+        return opt.map(|v| v.to_string()).unwrap_or_else(|| "NULL".to_owned());
+    }
+    if let Ok(opt) = row.get_opt::<_, f64>(i) {
+        return opt.map(|v| v.to_string()).unwrap_or_else(|| "NULL".to_owned());
+    }
+    if let Ok(opt) = row.get_opt::<_, NaiveDateTime>(i) {
+        return opt
+            .map(|v| v.format(TIMESTAMP_FORMAT).to_string())
+            .unwrap_or_else(|| "NULL".to_owned());
+    }
+    unimplemented!("postgres column {} has an unsupported type", i)
+}
+
+fn read_file<P: AsRef<Path>>(file_name: P) -> String {
+    let mut contents = String::new();
+    let mut file = File::open(file_name).unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    contents
+}
+
+fn write_file<P: AsRef<Path>>(file_name: P, contents: String) {
+    let mut file = File::create(file_name).unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+}
+
+fn run_for_all_in_directory<F: FnMut(String, String)>(directory: &str, mut f: F) {
+    let directory = Path::new(DIRECTORY_PREFIX).join(directory);
+    for entry in fs::read_dir(directory).unwrap() {
+        let entry = entry.unwrap();
+        f(
+            entry.file_name().to_str().unwrap().to_owned(),
+            read_file(entry.path().to_str().unwrap()),
+        );
+    }
+}
+
+/// Target results produced by a single oracle backend, tagged with the
+/// backend's name so a target file on disk records which oracle produced it.
+#[derive(Debug, Serialize, Deserialize)]
+struct TargetData {
+    backend: String,
+    results: BTreeMap<String, BTreeMap<String, Vec<Vec<String>>>>,
+}
+
+fn target_data_file(schema_name: &str, backend: &Backend) -> impl AsRef<Path> {
+    Path::new(DIRECTORY_PREFIX).join("targets").join(
+        format!("{}.{}", schema_name, backend.name()),
+    )
+}
+
+fn generate_target_results(schemas: &BTreeMap<String, Schema>, backend: &Backend) {
     for (schema_name, schema) in schemas.iter() {
-        let pool = setup_mysql("soup:password@127.0.0.1:3306/mysql_comparison_test");
         for (table_name, table) in schema.tables.iter() {
-            pool.prep_exec(&table.create_query, ()).unwrap();
+            backend.run_ddl(&table.create_query);
             for row in table.data.iter() {
-                let row: Vec<_> = row.iter()
-                    .zip(table.types.iter())
-                    .map(|(v, t)| match *t {
-                        Type::Text => format!("\"{}\"", v),
-                        Type::Int => v.clone(),
-                    })
-                    .collect();
-                let insert_query =
-                    format!("INSERT INTO {} VALUES ({})", table_name, row.join(", "));
-                pool.prep_exec(&insert_query, ()).unwrap();
+                backend.insert_row(table_name, &table.types, row);
             }
         }
 
-        let mut target_data: BTreeMap<String, BTreeMap<String, Vec<Vec<String>>>> = BTreeMap::new();
+        let mut results: BTreeMap<String, BTreeMap<String, Vec<Vec<String>>>> = BTreeMap::new();
         for (query_name, query) in schema.queries.iter() {
-            target_data.insert(query_name.clone(), BTreeMap::new());
+            results.insert(query_name.clone(), BTreeMap::new());
 
             for (i, values) in query.values.iter().enumerate() {
-                target_data.get_mut(query_name).unwrap().insert(
+                let rows = backend.select(&query.select_query, &query.types, values);
+                results.get_mut(query_name).unwrap().insert(
                     i.to_string(),
-                    Vec::new(),
+                    rows,
                 );
-
-                let values = Params::Positional(values.iter().map(|v| v.into()).collect());
-                for row in pool.prep_exec(&query.select_query, values).unwrap() {
-                    let row = row.unwrap()
-                        .unwrap()
-                        .into_iter()
-                        .map(|v| {
-                            v.into_str()
-                                .trim_matches(|c| c == '\'' || c == '"')
-                                .to_owned()
-                        })
-                        .collect();
-                    target_data
-                        .get_mut(query_name)
-                        .unwrap()
-                        .get_mut(&i.to_string())
-                        .unwrap()
-                        .push(row);
-                }
             }
         }
+
+        let target_data = TargetData {
+            backend: backend.name().to_owned(),
+            results: results,
+        };
         let target_data_toml = toml::to_string(&target_data).unwrap();
-        let target_data_file = Path::new(DIRECTORY_PREFIX).join("targets").join(
-            schema_name,
-        );
-        write_file(target_data_file, target_data_toml);
+        write_file(target_data_file(schema_name, backend), target_data_toml);
     }
 }
 
@@ -222,8 +502,13 @@ fn check_query(
     let getter = g.get_getter(nd).unwrap();
 
     for (i, query_parameter) in query.values.iter().enumerate() {
-        let query_parameter = query.types[0].make_datatype(&query_parameter[0]);
-        let query_results = getter(&query_parameter, true).unwrap();
+        assert_eq!(query_parameter.len(), query.types.len());
+        let query_parameters: Vec<DataType> = query_parameter
+            .iter()
+            .zip(query.types.iter())
+            .map(|(v, t)| t.make_datatype(v))
+            .collect();
+        let query_results = getter(&query_parameters, true).unwrap();
 
         let target_results = &target[&i.to_string()];
         let mut query_results: HashSet<Vec<String>> = query_results
@@ -234,6 +519,9 @@ fn check_query(
                         DataType::BigInt(i) => i.to_string(),
                         DataType::Text(_) |
                         DataType::TinyText(_) => v.into(),
+                        DataType::Real(..) => f64::from(v).to_string(),
+                        DataType::Timestamp(t) => t.format(TIMESTAMP_FORMAT).to_string(),
+                        DataType::None => "NULL".to_owned(),
                         _ => unimplemented!(),
 
                     })
@@ -274,6 +562,31 @@ fn check_query(
     Ok(())
 }
 
+fn run_against_backend(schemas: &BTreeMap<String, Schema>, backend: &Backend) {
+    if cfg!(feature = "generate_mysql_tests") {
+        generate_target_results(schemas, backend);
+    }
+
+    for (schema_name, schema) in schemas.iter() {
+        let target_data: TargetData =
+            toml::from_str(&read_file(target_data_file(schema_name, backend))).unwrap();
+
+        for (query_name, query) in schema.queries.iter() {
+            print!("{}.{} [{}]... ", schema.name, query_name, backend.name());
+            io::stdout().flush().ok().expect("Could not flush stdout");
+            match check_query(
+                &schema.tables,
+                query_name,
+                query,
+                &target_data.results[query_name],
+            ) {
+                Ok(()) => println!("\x1B[32;1mPASS\x1B[m"),
+                Err(e) => println!("\x1B[31;1mFAIL\x1B[m:\n{}", e),
+            }
+        }
+    }
+}
+
 #[test]
 fn mysql_comparison() {
     let mut schemas: BTreeMap<String, Schema> = BTreeMap::new();
@@ -287,24 +600,9 @@ fn mysql_comparison() {
         schemas.insert(file_name, toml::from_str(&contents).unwrap());
     });
 
-    if cfg!(feature = "generate_mysql_tests") {
-        generate_target_results(&schemas);
-    }
+    let mysql = MysqlBackend::new("soup:password@127.0.0.1:3306/mysql_comparison_test");
+    run_against_backend(&schemas, &mysql);
 
-    for (schema_name, schema) in schemas.iter() {
-        let target_data_file = Path::new(DIRECTORY_PREFIX).join("targets").join(
-            schema_name,
-        );
-        let target_data: BTreeMap<String, BTreeMap<String, Vec<Vec<String>>>> =
-            toml::from_str(&read_file(target_data_file)).unwrap();
-
-        for (query_name, query) in schema.queries.iter() {
-            print!("{}.{}... ", schema.name, query_name);
-            io::stdout().flush().ok().expect("Could not flush stdout");
-            match check_query(&schema.tables, query_name, query, &target_data[query_name]) {
-                Ok(()) => println!("\x1B[32;1mPASS\x1B[m"),
-                Err(e) => println!("\x1B[31;1mFAIL\x1B[m:\n{}", e),
-            }
-        }
-    }
+    let postgres = PostgresBackend::new("soup:password@127.0.0.1:5432/mysql_comparison_test");
+    run_against_backend(&schemas, &postgres);
 }